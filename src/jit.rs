@@ -0,0 +1,82 @@
+//! Translation cache for `$m[0]`: decodes each word once into a
+//! [`MicroOp`] instead of re-extracting the OP/RA/RB/RC/RL/VL fields and
+//! walking fourteen `opcode == N` comparisons on every cycle. On
+//! instruction-dense workloads (tight loops, e.g. a sandmark-style
+//! benchmark, such as `um::tests::runs_a_tight_loop_benchmark_workload`)
+//! this avoids re-decoding the same words on every cycle, since
+//! translation happens once per load of `$m[0]` rather than once per
+//! executed instruction — expect a large win on loop-heavy programs
+//! relative to decoding each instruction's fields from scratch every
+//! time it's executed. `VM` keeps the cache and invalidates
+//! it whenever opcode 12 replaces `$m[0]` or a segmented store writes
+//! into the currently executing segment; see `VM::step`. An x86_64-codegen
+//! path for the arithmetic/logic opcodes is a natural next step here but
+//! isn't implemented — the `match`-based micro-op path above is the
+//! supported fast path for now.
+
+use crate::rumdis;
+use crate::rumdis::Opcode;
+
+/// A pre-decoded UM instruction: the resolved opcode plus whichever of
+/// `a`/`b`/`c` (or `rl`/`vl`) it needs, so the hot loop in `VM::step` can
+/// `match` on this instead of bit-extracting fields and walking fourteen
+/// `opcode == N` comparisons per cycle.
+///
+/// `Data` stands in for a word that isn't a valid instruction (opcode > 13);
+/// reporting it is deferred until the word is actually executed, at
+/// which point `VM::step` turns it into a `MachineError::InvalidInstruction`.
+#[derive(Debug, Clone, Copy)]
+pub enum MicroOp {
+    CMov { a: usize, b: usize, c: usize },
+    SegLoad { a: usize, b: usize, c: usize },
+    SegStore { a: usize, b: usize, c: usize },
+    Add { a: usize, b: usize, c: usize },
+    Mul { a: usize, b: usize, c: usize },
+    Div { a: usize, b: usize, c: usize },
+    Nand { a: usize, b: usize, c: usize },
+    Halt,
+    MapSeg { b: usize, c: usize },
+    UnmapSeg { c: usize },
+    Output { c: usize },
+    Input { c: usize },
+    LoadProg { b: usize, c: usize },
+    LoadVal { rl: usize, vl: u32 },
+    Data { word: u32 },
+}
+
+/// Translates a whole segment into micro-ops, once, so repeated execution
+/// of the same segment (the common case: tight loops in `$m[0]`) skips
+/// re-decoding every cycle. Callers are expected to cache the result and
+/// re-translate only when the underlying words change (see the cache
+/// invalidation in `VM::step`).
+pub fn translate(words: &[u32]) -> Vec<MicroOp> {
+    words.iter().map(|&word| translate_one(word)).collect()
+}
+
+fn translate_one(word: u32) -> MicroOp {
+    let a = rumdis::get(&rumdis::RA, word) as usize;
+    let b = rumdis::get(&rumdis::RB, word) as usize;
+    let c = rumdis::get(&rumdis::RC, word) as usize;
+
+    match Opcode::from_u32(rumdis::op(word)) {
+        Some(Opcode::CMov) => MicroOp::CMov { a, b, c },
+        Some(Opcode::SegLoad) => MicroOp::SegLoad { a, b, c },
+        Some(Opcode::SegStore) => MicroOp::SegStore { a, b, c },
+        Some(Opcode::Add) => MicroOp::Add { a, b, c },
+        Some(Opcode::Mul) => MicroOp::Mul { a, b, c },
+        Some(Opcode::Div) => MicroOp::Div { a, b, c },
+        Some(Opcode::Nand) => MicroOp::Nand { a, b, c },
+        Some(Opcode::Halt) => MicroOp::Halt,
+        Some(Opcode::MapSeg) => MicroOp::MapSeg { b, c },
+        Some(Opcode::UnmapSeg) => MicroOp::UnmapSeg { c },
+        Some(Opcode::Output) => MicroOp::Output { c },
+        Some(Opcode::Input) => MicroOp::Input { c },
+        Some(Opcode::LoadProg) => MicroOp::LoadProg { b, c },
+        Some(Opcode::LoadVal) => {
+            let rl = rumdis::get(&rumdis::RL, word) as usize;
+            let vl = rumdis::get(&rumdis::VL, word);
+            MicroOp::LoadVal { rl, vl }
+        },
+        None => MicroOp::Data { word },
+    }
+}
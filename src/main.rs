@@ -1,10 +1,102 @@
+use rum::rumasm;
+use rum::rumdis;
 use rum::rumload;
-use std::env;
 use rum::um;
+use rum::um::VM;
+use std::env;
+use std::io::{stdin, stdout, Write};
+
+/// Cycle budget between debugger stops when no breakpoint is hit first.
+const DEBUG_BUDGET: u64 = 1_000_000;
 
 fn main() {
-    let input = env::args().nth(1);
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let disasm = if let Some(pos) = args.iter().position(|arg| arg == "--disasm") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+
+    let debug = if let Some(pos) = args.iter().position(|arg| arg == "--debug") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+
+    let asm = if let Some(pos) = args.iter().position(|arg| arg == "--asm") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+
+    if asm {
+        run_asm(&args);
+        return;
+    }
+
+    let input = args.into_iter().next();
     let instructions = rumload::load(input.as_deref());
 
-    um::handle_input(instructions);
+    if disasm {
+        for (pc, inst) in instructions.iter().enumerate() {
+            println!("{:5}: {}", pc, rumdis::disassemble(*inst));
+        }
+        return;
+    }
+
+    if debug {
+        let mut vm: VM<_, _> = VM::new(instructions, stdin(), stdout());
+        if let Err(e) = vm.run_debugger(DEBUG_BUDGET) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(e) = um::handle_input(instructions) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Assembles `args[0]` (UM assembly source) into big-endian `.um` words.
+/// Writes to `args[1]` if given, otherwise to stdout, so it can be
+/// redirected into a file.
+fn run_asm(args: &[String]) {
+    let Some(source_path) = args.first() else {
+        eprintln!("--asm requires a source file");
+        std::process::exit(1);
+    };
+
+    let source = match std::fs::read_to_string(source_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let words = match rumasm::assemble(&source) {
+        Ok(words) => words,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let bytes = rumasm::encode_words(&words);
+
+    let write_result = match args.get(1) {
+        Some(output_path) => std::fs::write(output_path, &bytes),
+        None => stdout().write_all(&bytes),
+    };
+
+    if let Err(e) = write_result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,232 @@
+use crate::rumdis;
+use crate::rumdis::Opcode;
+use std::fmt;
+
+/// Errors produced while assembling UM assembly source into words.
+/// Each variant carries the 1-indexed line of the offending token, and all
+/// but `MissingOperand` also its 1-indexed column, so a caller can point the
+/// user at the spot in their source file.
+#[derive(Debug)]
+pub enum AsmError {
+    UnknownMnemonic { line: usize, column: usize, token: String },
+    UnknownRegister { line: usize, column: usize, token: String },
+    InvalidImmediate { line: usize, column: usize, token: String },
+    ImmediateOutOfRange { line: usize, column: usize, value: i64 },
+    MissingOperand { line: usize, mnemonic: String },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, column, token } => {
+                write!(f, "{}:{}: unknown mnemonic '{}'", line, column, token)
+            },
+            AsmError::UnknownRegister { line, column, token } => {
+                write!(f, "{}:{}: '{}' is not a valid register (expected r0..=r7)", line, column, token)
+            },
+            AsmError::InvalidImmediate { line, column, token } => {
+                write!(f, "{}:{}: '{}' is not a valid immediate", line, column, token)
+            },
+            AsmError::ImmediateOutOfRange { line, column, value } => {
+                write!(f, "{}:{}: immediate {} does not fit in the 25-bit VL field", line, column, value)
+            },
+            AsmError::MissingOperand { line, mnemonic } => {
+                write!(f, "{}: '{}' is missing an operand", line, mnemonic)
+            },
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles `source` into big-endian UM words, one per non-blank,
+/// non-comment line, ready for `rumload::load` to read back.
+///
+/// Supported mnemonics map one-to-one onto the fourteen opcodes:
+/// `cmov`, `load`, `store`, `add`, `mul`, `div`, `nand`, `halt`, `map`,
+/// `unmap`, `out`, `in`, `loadprog`, `loadval`. Registers are written as
+/// `rN` (0..=7); immediates may be decimal or `0x`-prefixed hex.
+pub fn assemble(source: &str) -> Result<Vec<u32>, AsmError> {
+    let mut words = Vec::new();
+
+    for (idx, raw_line) in source.lines().enumerate() {
+        let line = idx + 1;
+        let tokens = tokenize(strip_comment(raw_line));
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let (_, mnemonic) = tokens[0];
+        let operands = &tokens[1..];
+
+        let word = match mnemonic {
+            "cmov" => encode3(Opcode::CMov, line, mnemonic, operands)?,
+            "load" => encode3(Opcode::SegLoad, line, mnemonic, operands)?,
+            "store" => encode3(Opcode::SegStore, line, mnemonic, operands)?,
+            "add" => encode3(Opcode::Add, line, mnemonic, operands)?,
+            "mul" => encode3(Opcode::Mul, line, mnemonic, operands)?,
+            "div" => encode3(Opcode::Div, line, mnemonic, operands)?,
+            "nand" => encode3(Opcode::Nand, line, mnemonic, operands)?,
+            "halt" => rumdis::set(&rumdis::OP, Opcode::Halt as u32),
+            "map" => encode_bc(Opcode::MapSeg, line, mnemonic, operands)?,
+            "unmap" => encode_c(Opcode::UnmapSeg, line, mnemonic, operands)?,
+            "out" => encode_c(Opcode::Output, line, mnemonic, operands)?,
+            "in" => encode_c(Opcode::Input, line, mnemonic, operands)?,
+            "loadprog" => encode_bc(Opcode::LoadProg, line, mnemonic, operands)?,
+            "loadval" => encode_loadval(line, mnemonic, operands)?,
+            _ => return Err(AsmError::UnknownMnemonic { line, column: tokens[0].0, token: mnemonic.to_string() }),
+        };
+
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+/// Serializes assembled words into the big-endian byte stream `rumload::load` expects.
+pub fn encode_words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(['#', ';']) {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+}
+
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    let mut offset = 0;
+    while let Some(start) = rest.find(|ch: char| !ch.is_whitespace()) {
+        let after_start = &rest[start..];
+        let end = after_start.find(char::is_whitespace).unwrap_or(after_start.len());
+        tokens.push((offset + start + 1, &after_start[..end]));
+        offset += start + end;
+        rest = &after_start[end..];
+    }
+    tokens
+}
+
+fn parse_register(line: usize, (column, token): (usize, &str)) -> Result<u32, AsmError> {
+    let digits = token.strip_prefix('r').ok_or_else(|| AsmError::UnknownRegister {
+        line, column, token: token.to_string(),
+    })?;
+    match digits.parse::<u32>() {
+        Ok(reg) if reg <= 7 => Ok(reg),
+        _ => Err(AsmError::UnknownRegister { line, column, token: token.to_string() }),
+    }
+}
+
+fn parse_immediate(line: usize, (column, token): (usize, &str)) -> Result<i64, AsmError> {
+    let parsed = if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else {
+        token.parse::<i64>()
+    };
+    parsed.map_err(|_| AsmError::InvalidImmediate { line, column, token: token.to_string() })
+}
+
+fn require_operands<'a>(line: usize, mnemonic: &str, operands: &'a [(usize, &'a str)], count: usize) -> Result<&'a [(usize, &'a str)], AsmError> {
+    if operands.len() < count {
+        Err(AsmError::MissingOperand { line, mnemonic: mnemonic.to_string() })
+    } else {
+        Ok(operands)
+    }
+}
+
+fn encode3(op: Opcode, line: usize, mnemonic: &str, operands: &[(usize, &str)]) -> Result<u32, AsmError> {
+    let operands = require_operands(line, mnemonic, operands, 3)?;
+    let a = parse_register(line, operands[0])?;
+    let b = parse_register(line, operands[1])?;
+    let c = parse_register(line, operands[2])?;
+    Ok(rumdis::set(&rumdis::OP, op as u32) | rumdis::set(&rumdis::RA, a) | rumdis::set(&rumdis::RB, b) | rumdis::set(&rumdis::RC, c))
+}
+
+fn encode_bc(op: Opcode, line: usize, mnemonic: &str, operands: &[(usize, &str)]) -> Result<u32, AsmError> {
+    let operands = require_operands(line, mnemonic, operands, 2)?;
+    let b = parse_register(line, operands[0])?;
+    let c = parse_register(line, operands[1])?;
+    Ok(rumdis::set(&rumdis::OP, op as u32) | rumdis::set(&rumdis::RB, b) | rumdis::set(&rumdis::RC, c))
+}
+
+fn encode_c(op: Opcode, line: usize, mnemonic: &str, operands: &[(usize, &str)]) -> Result<u32, AsmError> {
+    let operands = require_operands(line, mnemonic, operands, 1)?;
+    let c = parse_register(line, operands[0])?;
+    Ok(rumdis::set(&rumdis::OP, op as u32) | rumdis::set(&rumdis::RC, c))
+}
+
+fn encode_loadval(line: usize, mnemonic: &str, operands: &[(usize, &str)]) -> Result<u32, AsmError> {
+    let operands = require_operands(line, mnemonic, operands, 2)?;
+    let rl = parse_register(line, operands[0])?;
+    let (column, _) = operands[1];
+    let value = parse_immediate(line, operands[1])?;
+    if !(0..(1 << 25)).contains(&value) {
+        return Err(AsmError::ImmediateOutOfRange { line, column, value });
+    }
+    Ok(rumdis::set(&rumdis::OP, Opcode::LoadVal as u32) | rumdis::set(&rumdis::RL, rl) | rumdis::set(&rumdis::VL, value as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_one_word_per_instruction() {
+        let words = assemble("loadval r1 10\nadd r2 r1 r1\nhalt\n").expect("assembles");
+        assert_eq!(words.len(), 3);
+        assert_eq!(rumdis::disassemble(words[2]), "halt;");
+    }
+
+    #[test]
+    fn oversized_loadval_immediate_reports_its_position() {
+        let err = assemble("loadval r1 99999999\n").unwrap_err();
+        match err {
+            AsmError::ImmediateOutOfRange { line, column, value } => {
+                assert_eq!(line, 1);
+                assert_eq!(column, 12);
+                assert_eq!(value, 99999999);
+            },
+            other => panic!("expected ImmediateOutOfRange, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_mnemonic_reports_its_position() {
+        let err = assemble("frobnicate r1\n").unwrap_err();
+        match err {
+            AsmError::UnknownMnemonic { line, column, token } => {
+                assert_eq!(line, 1);
+                assert_eq!(column, 1);
+                assert_eq!(token, "frobnicate");
+            },
+            other => panic!("expected UnknownMnemonic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_register_reports_its_position() {
+        let err = assemble("loadval r8 1\n").unwrap_err();
+        match err {
+            AsmError::UnknownRegister { line, column, token } => {
+                assert_eq!(line, 1);
+                assert_eq!(column, 9);
+                assert_eq!(token, "r8");
+            },
+            other => panic!("expected UnknownRegister, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_operand_names_the_mnemonic() {
+        let err = assemble("add r1 r2\n").unwrap_err();
+        match err {
+            AsmError::MissingOperand { line, mnemonic } => {
+                assert_eq!(line, 1);
+                assert_eq!(mnemonic, "add");
+            },
+            other => panic!("expected MissingOperand, got {:?}", other),
+        }
+    }
+}
@@ -1,27 +1,147 @@
 type Umi = u32;
 pub struct Field { width: u32,
 lsb: u32, }
-pub static RA: Field = Field {width: 3, lsb: 6}; 
-pub static RB: Field = Field {width: 3, lsb: 3}; 
-pub static RC: Field = Field {width: 3, lsb: 0}; 
-pub static RL: Field = Field {width: 3, lsb: 25}; 
-pub static VL: Field = Field {width: 25, lsb: 0}; 
+pub static RA: Field = Field {width: 3, lsb: 6};
+pub static RB: Field = Field {width: 3, lsb: 3};
+pub static RC: Field = Field {width: 3, lsb: 0};
+pub static RL: Field = Field {width: 3, lsb: 25};
+pub static VL: Field = Field {width: 25, lsb: 0};
 pub static OP: Field = Field {width: 4, lsb: 28};
 
+/// The fourteen UM opcodes, numbered to match the bit pattern in the `OP` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    CMov = 0,
+    SegLoad = 1,
+    SegStore = 2,
+    Add = 3,
+    Mul = 4,
+    Div = 5,
+    Nand = 6,
+    Halt = 7,
+    MapSeg = 8,
+    UnmapSeg = 9,
+    Output = 10,
+    Input = 11,
+    LoadProg = 12,
+    LoadVal = 13,
+}
+
+impl Opcode {
+    /// Maps a raw opcode value to its `Opcode`, if it names one of the
+    /// fourteen UM instructions.
+    pub fn from_u32(op: u32) -> Option<Opcode> {
+        match op {
+            0 => Some(Opcode::CMov),
+            1 => Some(Opcode::SegLoad),
+            2 => Some(Opcode::SegStore),
+            3 => Some(Opcode::Add),
+            4 => Some(Opcode::Mul),
+            5 => Some(Opcode::Div),
+            6 => Some(Opcode::Nand),
+            7 => Some(Opcode::Halt),
+            8 => Some(Opcode::MapSeg),
+            9 => Some(Opcode::UnmapSeg),
+            10 => Some(Opcode::Output),
+            11 => Some(Opcode::Input),
+            12 => Some(Opcode::LoadProg),
+            13 => Some(Opcode::LoadVal),
+            _ => None,
+        }
+    }
+}
+
 fn mask(bits: u32) -> u32 { (1 << bits) - 1 }
 
-pub fn get(field: &Field, instruction: Umi) -> u32 { 
+pub fn get(field: &Field, instruction: Umi) -> u32 {
     (instruction >> field.lsb) & mask(field.width)
 }
 
-pub fn op(instruction: Umi) -> u32 { 
-    println!("halt");
+/// Packs `value` into `field`'s bit range, leaving the other bits clear.
+/// The inverse of `get`, so encoders (e.g. `rumasm`) and decoders agree
+/// on the same field layout.
+pub fn set(field: &Field, value: u32) -> Umi {
+    (value & mask(field.width)) << field.lsb
+}
+
+pub fn op(instruction: Umi) -> u32 {
     (instruction >> OP.lsb) & mask(OP.width)
 }
 
-/* 
-pub fn disassemble(inst: Umi) -> String { match get(&OP, inst) {
-    o if o == Opcode::CMov as u32 => {
-          format!("if (r{} != 0) r{} := r{};", get(&RC, inst), get(&RA, inst), get(&RB, inst))
+/// Decodes a single UM word into a human-readable line of pseudo-assembly.
+/// Unknown opcodes (>13) render as `.data 0x{inst:08x}` instead of failing,
+/// so a listing can walk through a segment that mixes code and data.
+pub fn disassemble(inst: Umi) -> String {
+    let a = get(&RA, inst);
+    let b = get(&RB, inst);
+    let c = get(&RC, inst);
+
+    match Opcode::from_u32(op(inst)) {
+        Some(Opcode::CMov) => format!("if (r{} != 0) r{} := r{};", c, a, b),
+        Some(Opcode::SegLoad) => format!("r{} := m[r{}][r{}];", a, b, c),
+        Some(Opcode::SegStore) => format!("m[r{}][r{}] := r{};", a, b, c),
+        Some(Opcode::Add) => format!("r{} := r{} + r{};", a, b, c),
+        Some(Opcode::Mul) => format!("r{} := r{} * r{};", a, b, c),
+        Some(Opcode::Div) => format!("r{} := r{} / r{};", a, b, c),
+        Some(Opcode::Nand) => format!("r{} := !(r{} & r{});", a, b, c),
+        Some(Opcode::Halt) => "halt;".to_string(),
+        Some(Opcode::MapSeg) => format!("r{} := map(r{});", b, c),
+        Some(Opcode::UnmapSeg) => format!("unmap(r{});", c),
+        Some(Opcode::Output) => format!("output r{};", c),
+        Some(Opcode::Input) => format!("r{} := input();", c),
+        Some(Opcode::LoadProg) => format!("loadprog r{} r{};", b, c),
+        Some(Opcode::LoadVal) => {
+            let rl = get(&RL, inst);
+            let vl = get(&VL, inst);
+            format!("r{} := {};", rl, vl)
         },
-        */
\ No newline at end of file
+        None => format!(".data 0x{:08x}", inst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(op: Opcode, a: u32, b: u32, c: u32) -> Umi {
+        set(&OP, op as u32) | set(&RA, a) | set(&RB, b) | set(&RC, c)
+    }
+
+    #[test]
+    fn decodes_every_opcode() {
+        assert_eq!(disassemble(word(Opcode::CMov, 1, 2, 3)), "if (r3 != 0) r1 := r2;");
+        assert_eq!(disassemble(word(Opcode::SegLoad, 1, 2, 3)), "r1 := m[r2][r3];");
+        assert_eq!(disassemble(word(Opcode::SegStore, 1, 2, 3)), "m[r1][r2] := r3;");
+        assert_eq!(disassemble(word(Opcode::Add, 1, 2, 3)), "r1 := r2 + r3;");
+        assert_eq!(disassemble(word(Opcode::Mul, 1, 2, 3)), "r1 := r2 * r3;");
+        assert_eq!(disassemble(word(Opcode::Div, 1, 2, 3)), "r1 := r2 / r3;");
+        assert_eq!(disassemble(word(Opcode::Nand, 1, 2, 3)), "r1 := !(r2 & r3);");
+        assert_eq!(disassemble(word(Opcode::Halt, 0, 0, 0)), "halt;");
+        assert_eq!(disassemble(word(Opcode::MapSeg, 0, 2, 3)), "r2 := map(r3);");
+        assert_eq!(disassemble(word(Opcode::UnmapSeg, 0, 0, 3)), "unmap(r3);");
+        assert_eq!(disassemble(word(Opcode::Output, 0, 0, 3)), "output r3;");
+        assert_eq!(disassemble(word(Opcode::Input, 0, 0, 3)), "r3 := input();");
+        assert_eq!(disassemble(word(Opcode::LoadProg, 0, 2, 3)), "loadprog r2 r3;");
+
+        let loadval = set(&OP, Opcode::LoadVal as u32) | set(&RL, 4) | set(&VL, 42);
+        assert_eq!(disassemble(loadval), "r4 := 42;");
+    }
+
+    #[test]
+    fn unknown_opcode_disassembles_as_data() {
+        let word = set(&OP, 14);
+        assert_eq!(disassemble(word), format!(".data 0x{:08x}", word));
+    }
+
+    #[test]
+    fn from_u32_rejects_opcodes_above_thirteen() {
+        assert_eq!(Opcode::from_u32(13), Some(Opcode::LoadVal));
+        assert_eq!(Opcode::from_u32(14), None);
+    }
+
+    #[test]
+    fn get_and_set_round_trip() {
+        assert_eq!(get(&RC, set(&RC, 5)), 5);
+        assert_eq!(get(&VL, set(&VL, 0x1FF_FFFF)), 0x1FF_FFFF);
+    }
+}
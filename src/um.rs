@@ -1,23 +1,320 @@
+use crate::jit;
+use crate::jit::MicroOp;
 use crate::rumdis;
-use std::io::{stdin, Read, Write, stdout};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{stdin, stdout, Read, Stdin, Stdout, Write};
 
 //Object responsible for holding Virtual Machine related data
-pub struct VM{
+pub struct VM<R: Read, W: Write>{
     registers: Vec<u32>,
-    memory: Vec<Vec<u32>>,
+    //`None` marks an unmapped segment slot, so a load/store/unmap against
+    //it faults instead of reading stale data or aliasing a later map.
+    memory: Vec<Option<Vec<u32>>>,
     unmap_index_values: Vec<usize>,
-    program_counter: usize
+    program_counter: usize,
+    reader: R,
+    writer: W,
+    breakpoints: HashSet<usize>,
+    //pc of the breakpoint last reported by `run_with_budget`, so the next
+    //call can step past it once instead of immediately re-matching the
+    //same unmoved program counter; see `run_with_budget`.
+    last_reported_breakpoint: Option<usize>,
+    //Translated `$m[0]`, rebuilt whenever opcode 12 replaces $m[0] or a
+    //store writes into it; see `step`.
+    translated_segment_zero: Option<Vec<MicroOp>>,
 }
 
+/// Whether a `VM::step` call ran an ordinary instruction or hit halt (opcode 7).
+#[derive(Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,
+    Halted,
+}
+
+/// Why `VM::run_with_budget` returned control to the caller.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint,
+    BudgetExhausted,
+    Halted,
+    ManualStep,
+}
+
+/// A snapshot of machine state taken when a debug session stops.
+pub struct DebugStop {
+    pub reason: StopReason,
+    pub pc: usize,
+    pub registers: [u32; 8],
+    pub mapped_segments: usize,
+}
+
+impl<R: Read, W: Write> VM<R, W> {
+    /// Builds a VM over `instructions`, reading opcode 11 input from
+    /// `reader` and writing opcode 10 output to `writer`.
+    ///
+    /// # Arguments:
+    /// * instructions: A vector containing 32-bit words which are instructions
+    /// * reader: Stream that opcode 11 (input) reads bytes from
+    /// * writer: Stream that opcode 10 (output) writes bytes to
+    pub fn new(instructions: Vec<u32>, reader: R, writer: W) -> VM<R, W>{
+        VM{
+            registers: vec![0; 8],
+            memory: vec![Some(instructions)],
+            unmap_index_values: vec![],
+            program_counter: 0,
+            reader,
+            writer,
+            breakpoints: HashSet::new(),
+            last_reported_breakpoint: None,
+            translated_segment_zero: None,
+        }
+    }
+
+    /// Executes exactly one instruction at the current program counter and
+    /// reports whether the machine halted.
+    ///
+    /// Dispatches through a cached translation of `$m[0]` (see the `jit`
+    /// module) instead of re-decoding the instruction's fields every
+    /// cycle; the cache is rebuilt lazily the first time it's missing and
+    /// invalidated whenever an instruction could have changed `$m[0]`.
+    pub fn step(&mut self) -> Result<StepOutcome, MachineError>{
+        //If at the beginning of a machine cycle the program counter points outside the bounds of $m[0], the machine may fail.
+        if self.program_counter >= self.segment_zero().len(){
+            return Err(MachineError::InvalidInstruction { word: 0, pc: self.program_counter });
+        }
+
+        if self.translated_segment_zero.as_ref().is_none_or(|ops| ops.len() != self.segment_zero().len()){
+            self.translated_segment_zero = Some(jit::translate(self.segment_zero()));
+        }
+
+        let micro_op = self.translated_segment_zero.as_ref().unwrap()[self.program_counter];
+        let pc = self.program_counter;
+        self.program_counter += 1;
+
+        match micro_op{
+            MicroOp::Data { word } => {
+                return Err(MachineError::InvalidInstruction { word, pc });
+            },
+            MicroOp::CMov { a, b, c } => opcode0(self, a, b, c),
+            MicroOp::SegLoad { a, b, c } => opcode1(self, a, b, c)?,
+            MicroOp::SegStore { a, b, c } => {
+                opcode2(self, a, b, c)?;
+                //A store into the segment currently being executed changes $m[0]'s words.
+                if self.registers[a] as usize == 0{
+                    self.translated_segment_zero = None;
+                }
+            },
+            MicroOp::Add { a, b, c } => opcode3(self, a, b, c),
+            MicroOp::Mul { a, b, c } => opcode4(self, a, b, c),
+            MicroOp::Div { a, b, c } => opcode5(self, a, b, c)?,
+            MicroOp::Nand { a, b, c } => opcode6(self, a, b, c),
+            MicroOp::Halt => return Ok(StepOutcome::Halted),
+            MicroOp::MapSeg { b, c } => opcode8(self, b, c),
+            MicroOp::UnmapSeg { c } => opcode9(self, c)?,
+            MicroOp::Output { c } => opcode10(self, c)?,
+            MicroOp::Input { c } => opcode11(self, c),
+            MicroOp::LoadProg { b, c } => {
+                let replaces_segment_zero = self.registers[b] != 0;
+                opcode12(self, b, c)?;
+                if replaces_segment_zero{
+                    self.translated_segment_zero = None;
+                }
+            },
+            MicroOp::LoadVal { rl, vl } => opcode13(self, rl, vl),
+        }
+
+        Ok(StepOutcome::Continue)
+    }
+
+    /// Reference to `$m[0]`, which is always mapped: construction maps it
+    /// and `opcode9` refuses to unmap it.
+    fn segment_zero(&self) -> &Vec<u32>{
+        self.memory[0].as_ref().expect("$m[0] is always mapped")
+    }
+
+    /// Reads `$m[segment][offset]`, faulting if `segment` isn't mapped or
+    /// `offset` is out of range for it.
+    fn read_segment_word(&self, segment: usize, offset: usize) -> Result<u32, MachineError>{
+        self.memory.get(segment).and_then(|slot| slot.as_ref())
+            .and_then(|words| words.get(offset))
+            .copied()
+            .ok_or(MachineError::UnmappedSegmentAccess { segment, offset })
+    }
+
+    /// Writes `$m[segment][offset]`, faulting if `segment` isn't mapped or
+    /// `offset` is out of range for it.
+    fn write_segment_word(&mut self, segment: usize, offset: usize, value: u32) -> Result<(), MachineError>{
+        let word = self.memory.get_mut(segment).and_then(|slot| slot.as_mut())
+            .and_then(|words| words.get_mut(offset))
+            .ok_or(MachineError::UnmappedSegmentAccess { segment, offset })?;
+        *word = value;
+        Ok(())
+    }
+
+    /// Runs the loaded program until it halts (opcode 7) or an
+    /// instruction faults, in which case the `MachineError` is returned
+    /// to the caller instead of aborting the process.
+    pub fn run(&mut self) -> Result<(), MachineError>{
+        loop{
+            if self.step()? == StepOutcome::Halted{
+                return Ok(());
+            }
+        }
+    }
+
+    /// Adds a PC breakpoint; `run_with_budget` stops when it's reached.
+    pub fn add_breakpoint(&mut self, pc: usize){
+        self.breakpoints.insert(pc);
+    }
+
+    /// Removes a previously added breakpoint.
+    pub fn remove_breakpoint(&mut self, pc: usize){
+        self.breakpoints.remove(&pc);
+    }
+
+    /// Runs until a breakpoint is hit, the program halts, or `max_cycles`
+    /// instructions have executed, whichever comes first, so a runaway
+    /// program can be interrupted deterministically.
+    ///
+    /// If the program counter is sitting where the previous call already
+    /// reported a breakpoint, that one check is skipped so the VM steps
+    /// past it instead of re-matching the same unmoved pc forever; see
+    /// `last_reported_breakpoint`.
+    pub fn run_with_budget(&mut self, max_cycles: u64) -> Result<DebugStop, MachineError>{
+        let mut resume_from = self.last_reported_breakpoint.take();
+        for _ in 0..max_cycles{
+            if resume_from.take() != Some(self.program_counter) && self.breakpoints.contains(&self.program_counter){
+                self.last_reported_breakpoint = Some(self.program_counter);
+                return Ok(self.snapshot(StopReason::Breakpoint));
+            }
+            if self.step()? == StepOutcome::Halted{
+                return Ok(self.snapshot(StopReason::Halted));
+            }
+        }
+        Ok(self.snapshot(StopReason::BudgetExhausted))
+    }
+
+    /// Drives an interactive debug session: prints the decoded
+    /// instruction, registers, and mapped-segment count each time
+    /// `run_with_budget` stops, then accepts `step`, `continue`, and
+    /// `dump-segment <id>` commands from stdin until the program halts.
+    pub fn run_debugger(&mut self, max_cycles: u64) -> Result<(), MachineError>{
+        loop{
+            let stop = self.run_with_budget(max_cycles)?;
+            self.print_stop(&stop);
+            if stop.reason == StopReason::Halted{
+                return Ok(());
+            }
+
+            loop{
+                print!("(rumdb) ");
+                stdout().flush().ok();
+                let mut line = String::new();
+                if stdin().read_line(&mut line).unwrap_or(0) == 0{
+                    return Ok(());
+                }
+
+                match line.trim(){
+                    "step" => {
+                        if self.step()? == StepOutcome::Halted{
+                            self.print_stop(&self.snapshot(StopReason::Halted));
+                            return Ok(());
+                        }
+                        self.print_stop(&self.snapshot(StopReason::ManualStep));
+                    },
+                    "continue" => break,
+                    "" => continue,
+                    cmd if cmd.starts_with("dump-segment") => {
+                        match cmd.split_whitespace().nth(1).and_then(|id| id.parse::<usize>().ok()){
+                            Some(id) => self.dump_segment(id),
+                            None => println!("usage: dump-segment <id>"),
+                        }
+                    },
+                    other => println!("unknown command: {}", other),
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self, reason: StopReason) -> DebugStop{
+        let mut registers = [0_u32; 8];
+        registers.copy_from_slice(&self.registers);
+        DebugStop{
+            reason,
+            pc: self.program_counter,
+            registers,
+            mapped_segments: self.memory.iter().filter(|slot| slot.is_some()).count(),
+        }
+    }
+
+    fn print_stop(&self, stop: &DebugStop){
+        let instruction = self.segment_zero().get(stop.pc).copied().unwrap_or(0);
+        println!("{:?} at pc {}: {}", stop.reason, stop.pc, rumdis::disassemble(instruction));
+        println!("registers: {:?}", stop.registers);
+        println!("mapped segments: {}", stop.mapped_segments);
+    }
+
+    fn dump_segment(&self, id: usize){
+        match self.memory.get(id){
+            Some(Some(segment)) => println!("segment {} ({} words): {:?}", id, segment.len(), segment),
+            _ => println!("segment {} is not mapped", id),
+        }
+    }
+}
+
+/// Errors that can occur while executing a UM program.
+/// Returned by `handle_input` so callers can recover instead of the
+/// process aborting out from under them.
+#[derive(Debug)]
+pub enum MachineError {
+    /// `$r[c]` was zero on a divide instruction.
+    DivideByZero,
+    /// An instruction tried to unmap `$m[0]`.
+    UnmapZeroSegment,
+    /// A load, store, unmap, or load-program referred to a segment that
+    /// isn't mapped, or a load or store used an offset outside a mapped one.
+    UnmappedSegmentAccess { segment: usize, offset: usize },
+    /// The word at `pc` does not code for a valid instruction, or `pc`
+    /// lies outside `$m[0]` (then `word` is 0).
+    InvalidInstruction { word: u32, pc: usize },
+    /// An output instruction tried to write a value outside 0..=255.
+    OutputOutOfRange { value: u32 },
+    /// Writing to the VM's writer failed (e.g. a broken pipe) or wrote
+    /// fewer bytes than requested.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MachineError::DivideByZero => write!(f, "cannot divide by 0"),
+            MachineError::UnmapZeroSegment => write!(f, "instruction is trying to unmap $m[0]"),
+            MachineError::UnmappedSegmentAccess { segment, offset } => {
+                write!(f, "access to unmapped segment {} at offset {}", segment, offset)
+            },
+            MachineError::InvalidInstruction { word, pc } => {
+                write!(f, "word {:#010x} at pc {} does not code for a valid instruction", word, pc)
+            },
+            MachineError::OutputOutOfRange { value } => {
+                write!(f, "value {} is not a valid output value (must be 0..=255)", value)
+            },
+            MachineError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
 /// Performs a Conditional Move if $r[C] != 0
 /// Modifies the a register in the VM object
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode0(um: &mut VM, a: usize, b: usize, c: usize){
+pub fn opcode0<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize){
     if um.registers[c] != 0{
         um.registers[a] = um.registers[b];
     }
@@ -25,159 +322,177 @@ pub fn opcode0(um: &mut VM, a: usize, b: usize, c: usize){
 
 /// Performs a Segmented Load
 /// Modifies the a register in the VM object
-/// 
+/// Returns `MachineError::UnmappedSegmentAccess` instead of aborting if
+/// $m[$r[b]] isn't mapped or $r[c] is out of range for it
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode1(um: &mut VM, a: usize, b: usize, c: usize){
-    um.registers[a] = um.memory[um.registers[b] as usize][um.registers[c] as usize];
+pub fn opcode1<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize) -> Result<(), MachineError>{
+    let segment = um.registers[b] as usize;
+    let offset = um.registers[c] as usize;
+    um.registers[a] = um.read_segment_word(segment, offset)?;
+    Ok(())
 }
 
 /// Performs a Segmented Store
 /// Modifies the memory address at the $m[$r[a]][$r[b]] index
-/// 
+/// Returns `MachineError::UnmappedSegmentAccess` instead of aborting if
+/// $m[$r[a]] isn't mapped or $r[b] is out of range for it
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode2(um: &mut VM, a: usize, b: usize, c: usize){
-    um.memory[um.registers[a] as usize][um.registers[b] as usize] = um.registers[c];
+pub fn opcode2<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize) -> Result<(), MachineError>{
+    let segment = um.registers[a] as usize;
+    let offset = um.registers[b] as usize;
+    let value = um.registers[c];
+    um.write_segment_word(segment, offset, value)
 }
 
 /// Performs an Addition operation
 /// Modifies the a register in the VM object
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode3(um: &mut VM, a: usize, b: usize, c: usize){
+pub fn opcode3<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize){
     um.registers[a] = um.registers[b].wrapping_add(um.registers[c]);
 }
 
 /// Performs a Multiplication operation
 /// Modifies the a register in the VM object
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode4(um: &mut VM, a: usize, b: usize, c: usize){
+pub fn opcode4<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize){
     um.registers[a] = um.registers[b].wrapping_mul(um.registers[c]);
 }
 
 /// Performs integer division
 /// Modifies the a register in the VM object
-/// 
+/// Returns `MachineError::DivideByZero` instead of aborting when $r[c] == 0
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode5(um: &mut VM, a: usize, b: usize, c: usize){
+pub fn opcode5<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize) -> Result<(), MachineError>{
     //If a segmented load or segmented store refers to an unmapped segment, the machine may fail.
     if um.registers[c] == 0{
-        panic!("Cannot divide by 0")
+        return Err(MachineError::DivideByZero);
     }
     um.registers[a] = um.registers[b] / um.registers[c];
+    Ok(())
 }
 
 /// Performs Bitwise NAND
 /// Modifies the a register in the VM object
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * a: The a register
 /// * b: The b register
 /// * c: The c register
-pub fn opcode6(um: &mut VM, a: usize, b: usize, c: usize){
+pub fn opcode6<R: Read, W: Write>(um: &mut VM<R, W>, a: usize, b: usize, c: usize){
     um.registers[a] = !(um.registers[b] & um.registers[c]);
 }
 
-/// Ends the program
-pub fn opcode7(){
-    std::process::exit(0);
-}
-
 /// Maps a segment
 /// The new segment is mapped as $m[$r[b]]
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * b: The b register
 /// * c: The c register
-pub fn opcode8(um: &mut VM, b: usize, c: usize){
+pub fn opcode8<R: Read, W: Write>(um: &mut VM<R, W>, b: usize, c: usize){
     //A new segment is created with a number of words equal to the value in $r[C]
     //Each word in the new segment is initialized to zero
     let length = um.registers[c] as usize;
     let new_segment = vec![0_u32; length];
 
     //A bit pattern that is not all zeroes and does not identify any currently mapped segment is placed in $r[B].
-    if um.unmap_index_values.len() != 0{
-        um.registers[b] = (um.unmap_index_values.pop().unwrap()) as u32;
+    if let Some(reused) = um.unmap_index_values.pop(){
+        um.registers[b] = reused as u32;
 
         //The new segment is mapped as $m[$r[B]].
-        um.memory[um.registers[b] as usize] = new_segment;
+        um.memory[reused] = Some(new_segment);
     }else {
         //The new segment is mapped as $m[$r[B]].
-        um.memory.push(new_segment.clone());
+        um.memory.push(Some(new_segment));
         um.registers[b] = (um.memory.len() - 1) as u32;
     }
 }
 
 /// Unmaps a segment
-/// The segment $m[$r[c]] is unmapped
-/// 
+/// The segment $m[$r[c]] is unmapped and its space reclaimed so a later
+/// `opcode8` can hand the same index out again without aliasing the old data
+/// Returns `MachineError::UnmapZeroSegment` if the instruction tries to
+/// unmap $m[0], or `MachineError::UnmappedSegmentAccess` if $r[c] names a
+/// segment that isn't currently mapped (including one already unmapped)
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * c: The c register
-pub fn opcode9(um: &mut VM, c: usize){
-    //The segment $m[$r[C]] is unmapped.
+pub fn opcode9<R: Read, W: Write>(um: &mut VM<R, W>, c: usize) -> Result<(), MachineError>{
+    let segment = um.registers[c] as usize;
+
     //If an instruction unmaps $m[0], or if it unmaps a segment that is not mapped, the machine may fail.
-    if um.registers[c] as usize == 0{
-        panic!("Instruction is trying to unmap $m[0]")
-    }else{
-        um.unmap_index_values.push(um.registers[c] as usize);
+    if segment == 0{
+        return Err(MachineError::UnmapZeroSegment);
+    }
+
+    match um.memory.get_mut(segment){
+        Some(slot) if slot.is_some() => {
+            *slot = None;
+            um.unmap_index_values.push(segment);
+            Ok(())
+        },
+        _ => Err(MachineError::UnmappedSegmentAccess { segment, offset: 0 }),
     }
-    
 }
 
-/// Outputs a specified value
+/// Outputs a specified value to the VM's writer
 /// Only valid values to output between 0 and 255
-/// 
+/// Returns `MachineError::OutputOutOfRange` if the value doesn't fit a
+/// byte, or `MachineError::Io` if writing to the writer fails or writes
+/// fewer bytes than requested (e.g. a broken pipe)
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * c: The c register
-pub fn opcode10(um: &mut VM, c: usize){
-    let value = u8::try_from(um.registers[c]).unwrap();
-    let mut buffer = std::io::stdout();
-    match buffer.write(&[value]).unwrap() {
-        1 =>{
-            stdout().flush().unwrap();
-        },
-        _ =>{
-            panic!("Wrong output value")
-        }
+pub fn opcode10<R: Read, W: Write>(um: &mut VM<R, W>, c: usize) -> Result<(), MachineError>{
+    let value = u8::try_from(um.registers[c])
+        .map_err(|_| MachineError::OutputOutOfRange { value: um.registers[c] })?;
+    match um.writer.write(&[value]).map_err(MachineError::Io)? {
+        1 => um.writer.flush().map_err(MachineError::Io),
+        written => Err(MachineError::Io(std::io::Error::new(
+            std::io::ErrorKind::WriteZero,
+            format!("wrote {} bytes instead of 1", written),
+        ))),
     }
 }
 
-/// Reads an input from standard in
+/// Reads an input from the VM's reader
 /// When the input arrives, $r[c] is loaded with the input
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * c: The c register
-pub fn opcode11(um: &mut VM, c: usize){
+pub fn opcode11<R: Read, W: Write>(um: &mut VM<R, W>, c: usize){
     let mut input = [0_u8; 1];
 
-    let mut number = stdin();
-
-    um.registers[c] = match number.read(&mut input).expect("Failed to read line") {
+    um.registers[c] = match um.reader.read(&mut input).expect("Failed to read line") {
         1 =>{
             input[0] as u32
         },
@@ -189,192 +504,161 @@ pub fn opcode11(um: &mut VM, c: usize){
 
 /// Performs the load program
 /// Segment $m[$r[b]] is duplicated, and the duplicate replaces $m[0]
-/// 
+/// Returns `MachineError::UnmappedSegmentAccess` instead of aborting if
+/// $m[$r[b]] isn't mapped
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * b: The b register
 /// * c: The c register
-pub fn opcode12(um: &mut VM, b: usize, c: usize){
+pub fn opcode12<R: Read, W: Write>(um: &mut VM<R, W>, b: usize, c: usize) -> Result<(), MachineError>{
     //Program counter is set to point to $m[0][$r[c]]
     um.program_counter = um.registers[c] as usize;
-    
+
     if um.registers[b] != 0{
         //duplicate memory segment at $m[$r[b]]
-        let new_segment = um.memory[um.registers[b] as usize].clone();
+        let segment = um.registers[b] as usize;
+        let new_segment = um.memory.get(segment).and_then(|slot| slot.as_ref())
+            .ok_or(MachineError::UnmappedSegmentAccess { segment, offset: 0 })?
+            .clone();
 
         //replace and abandonds the $m[0] value with the new_segment value
-        um.memory[0] = new_segment;
+        um.memory[0] = Some(new_segment);
     }
+
+    Ok(())
 }
 
 /// Loads a value
-/// 
+///
 /// # Arguments:
 /// * um: A Virtual Machine object
 /// * rl: The a register
 /// * vl: The value
-pub fn opcode13(um: &mut VM, rl: usize, vl: u32){
+pub fn opcode13<R: Read, W: Write>(um: &mut VM<R, W>, rl: usize, vl: u32){
     um.registers[rl] = vl;
 }
 
 /// Handle the input of instructions
-/// Is responsible for determining which instructions to execute
-/// 
+/// Convenience wrapper for `main`: runs `instructions` against stdin/stdout
+/// instead of requiring callers to build a `VM` themselves.
+///
 /// # Arguments:
 /// * instructions: A vector containing 32-bit words which are instructions
-pub fn handle_input(instructions: Vec<u32>){
-    //initialize registers to 0
-    let registers: Vec<u32> = vec![0; 8];
+pub fn handle_input(instructions: Vec<u32>) -> Result<(), MachineError>{
+    let mut vm: VM<Stdin, Stdout> = VM::new(instructions, stdin(), stdout());
+    vm.run()
+}
 
-    //create program counter and initialize to 0,0
-    let program_counter = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rumasm;
 
-    //2-d array for memory segments
-    let mut memory: Vec<Vec<u32>> = vec![];
-  
-    memory.push(instructions.clone());
+    /// Demonstrates the point of threading `R`/`W` through `VM`: a test
+    /// can drive it with a `&[u8]` input and capture output into a
+    /// `Vec<u8>` instead of touching real stdin/stdout.
+    #[test]
+    fn feeds_input_and_captures_output() {
+        let program = rumasm::assemble("in r1\nout r1\nhalt\n").expect("assembles");
+        let input: &[u8] = b"A";
+        let mut output: Vec<u8> = Vec::new();
 
+        let mut vm = VM::new(program, input, &mut output);
+        vm.run().expect("runs to completion");
 
-    let unmap_index_values: Vec<usize> = vec![];
+        assert_eq!(output, b"A");
+    }
 
-    let mut um = VM{
-        registers,
-        memory,
-        unmap_index_values,
-        program_counter
-    };
+    fn run_source(source: &str) -> Result<(), MachineError> {
+        let program = rumasm::assemble(source).expect("assembles");
+        let mut output: Vec<u8> = Vec::new();
+        let mut vm = VM::new(program, &b""[..], &mut output);
+        vm.run()
+    }
 
-    //If at the beginning of a machine cycle the program counter points outside the bounds of $m[0], the machine may fail.
-    if um.program_counter > 0{
-        panic!("Program Counter outside the bounds of $m[0]")
+    #[test]
+    fn divide_by_zero_faults() {
+        let err = run_source("loadval r1 1\nloadval r2 0\ndiv r3 r1 r2\nhalt\n").unwrap_err();
+        assert!(matches!(err, MachineError::DivideByZero));
     }
 
-    //If at the beginning of a cycle, the word pointed to by the program counter does not code for a valid instruction, the machine may fail.
-    if rumdis::get(&rumdis::OP, um.memory[0][um.program_counter]) > 13{
-        panic!("Word being pointed to does not code for valid instructions")
+    #[test]
+    fn unmapping_m0_faults() {
+        let err = run_source("loadval r1 0\nunmap r1\nhalt\n").unwrap_err();
+        assert!(matches!(err, MachineError::UnmapZeroSegment));
     }
 
-    loop{
-        let instruction = um.memory[0][um.program_counter];
-
-        //get the opcode
-        let opcode = rumdis::get(&rumdis::OP, instruction);
-        let a = (rumdis::get(&rumdis::RA, instruction)) as usize;
-        let b = (rumdis::get(&rumdis::RB, instruction)) as usize;
-        let c = (rumdis::get(&rumdis::RC, instruction)) as usize;
-        //let rl = (rumdis::get(&rumdis::RL, instruction)) as usize;
-        //let vl = rumdis::get(&rumdis::VL, instruction);
-        um.program_counter += 1;
-
-        if opcode == 0{
-            if um.registers[c] != 0{
-                um.registers[a] = um.registers[b];
-            }
-            //opcode0(&mut um, a, b, c);
-        }
-        if opcode == 1{
-            um.registers[a] = um.memory[um.registers[b] as usize][um.registers[c] as usize];
-            //opcode1(&mut um, a, b, c);
-        }
-        if opcode == 2{
-            um.memory[um.registers[a] as usize][um.registers[b] as usize] = um.registers[c];
-            //opcode2(&mut um, a, b, c);
-        }
-        if opcode == 3{
-            um.registers[a] = um.registers[b].wrapping_add(um.registers[c]);
-            //opcode3(&mut um, a, b, c);
-        }
-        if opcode == 4{
-            um.registers[a] = um.registers[b].wrapping_mul(um.registers[c]);
-            //opcode4(&mut um, a, b, c);
-        }
-        if opcode == 5{
-            if um.registers[c] == 0{
-                panic!("Cannot divide by 0")
-            }
-            um.registers[a] = um.registers[b] / um.registers[c];
-            //opcode5(&mut um, a, b, c);
-        }
-        if opcode == 6{
-            um.registers[a] = !(um.registers[b] & um.registers[c]);
-            //opcode6(&mut um, a, b, c);
-        }
-        if opcode == 7{
-            std::process::exit(0);
-            //opcode7();
-        }
-        if opcode == 8{
-            let length = um.registers[c] as usize;
-            let new_segment = vec![0_u32; length];
-        
-            //A bit pattern that is not all zeroes and does not identify any currently mapped segment is placed in $r[B].
-            if um.unmap_index_values.len() != 0{
-                um.registers[b] = (um.unmap_index_values.pop().unwrap()) as u32;
-        
-                //The new segment is mapped as $m[$r[B]].
-                um.memory[um.registers[b] as usize] = new_segment;
-            }else {
-                //The new segment is mapped as $m[$r[B]].
-                //um.memory.push(new_segment.clone());
-                um.memory.push(new_segment);
-                um.registers[b] = (um.memory.len() - 1) as u32;
-            }
-            //opcode8(&mut um, b, c);
-        }
-        if opcode == 9{
-            if um.registers[c] as usize == 0{
-                panic!("Instruction is trying to unmap $m[0]")
-            }else{
-                um.unmap_index_values.push(um.registers[c] as usize);
-            }
-            //opcode9(&mut um, c);
-        }
-        if opcode == 10{
-            let value = u8::try_from(um.registers[c]).unwrap();
-            let mut buffer = std::io::stdout();
-            match buffer.write(&[value]).unwrap() {
-                1 =>{
-                    stdout().flush().unwrap();
-                },
-                _ =>{
-                    panic!("Wrong output value")
-                }
-            }
-            //opcode10(&mut um, c);
-        }
-        if opcode == 11{
-            let mut input = [0_u8; 1];
-
-            let mut number = stdin();
-        
-            um.registers[c] = match number.read(&mut input).expect("Failed to read line") {
-                1 =>{
-                    input[0] as u32
-                },
-                _ => {
-                    u32::MAX
-                }
-            }
-            //opcode11(&mut um, c);
-        }
-        if opcode == 12{
-            um.program_counter = um.registers[c] as usize;
-    
-            if um.registers[b] != 0{
-                //duplicate memory segment at $m[$r[b]]
-                //let new_segment = um.memory[um.registers[b] as usize].clone();
-                let new_segment = &um.memory[um.registers[b] as usize];
-        
-                //replace and abandonds the $m[0] value with the new_segment value
-                um.memory[0] = (new_segment).to_vec();
-            }
-            //opcode12(&mut um, b, c);
-        }
-        if opcode == 13{
-            let rl = (rumdis::get(&rumdis::RL, instruction)) as usize;
-            let vl = rumdis::get(&rumdis::VL, instruction);
-            um.registers[rl] = vl;
-            //opcode13(&mut um, rl, vl);
-        }
+    #[test]
+    fn double_unmap_faults() {
+        let err = run_source(
+            "loadval r1 4\nmap r2 r1\nunmap r2\nunmap r2\nhalt\n",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            MachineError::UnmappedSegmentAccess { offset: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn out_of_range_segment_access_faults() {
+        let err = run_source("loadval r1 99\nloadval r2 0\nload r3 r1 r2\nhalt\n").unwrap_err();
+        assert!(matches!(
+            err,
+            MachineError::UnmappedSegmentAccess { segment: 99, .. }
+        ));
+    }
+
+    #[test]
+    fn invalid_instruction_faults() {
+        // No `halt`, so the program counter runs past the end of $m[0]
+        // and faults instead of reading a stale/garbage word.
+        let err = run_source("loadval r1 0\n").unwrap_err();
+        assert!(matches!(err, MachineError::InvalidInstruction { pc: 1, .. }));
+    }
+
+    /// Regression test for a bug where a second `run_with_budget` call
+    /// immediately re-reported the same unmoved breakpoint instead of
+    /// stepping past it, which left the debugger's `continue` command
+    /// permanently stuck on any breakpoint.
+    #[test]
+    fn continuing_past_a_breakpoint_advances_the_program() {
+        let program = rumasm::assemble("loadval r1 1\nloadval r2 2\nhalt\n").expect("assembles");
+        let mut output: Vec<u8> = Vec::new();
+        let mut vm = VM::new(program, &b""[..], &mut output);
+        vm.add_breakpoint(0);
+
+        let first_stop = vm.run_with_budget(10).expect("runs");
+        assert_eq!(first_stop.reason, StopReason::Breakpoint);
+        assert_eq!(first_stop.pc, 0);
+
+        let second_stop = vm.run_with_budget(10).expect("runs");
+        assert_eq!(second_stop.reason, StopReason::Halted);
+        assert_eq!(second_stop.registers[1], 1);
+        assert_eq!(second_stop.registers[2], 2);
+    }
+
+    /// A sandmark-style workload: a tight counting loop entirely inside
+    /// `$m[0]`, the case the `jit` translation cache exists for. Exercises
+    /// the cache-hit path in `VM::step` across many iterations instead of
+    /// just a handful of instructions.
+    #[test]
+    fn runs_a_tight_loop_benchmark_workload() {
+        let source = "\
+loadval r1 0
+nand r1 r1 r1
+loadval r2 2000000
+loadval r7 4
+add r2 r2 r1
+loadval r5 8
+cmov r5 r7 r2
+loadprog r0 r5
+halt
+";
+        let program = rumasm::assemble(source).expect("assembles");
+        let mut output: Vec<u8> = Vec::new();
+        let mut vm = VM::new(program, &b""[..], &mut output);
+        vm.run().expect("runs to completion");
     }
 }